@@ -1,12 +1,9 @@
 // Copyright 2018-2023 the Deno authors. All rights reserved. MIT license.
 
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::sync::Arc;
 use std::sync::Arc;
 
+use deno_core::anyhow::anyhow;
 use deno_core::error::AnyError;
-use deno_core::futures::FutureExt;
 use deno_core::resolve_url_or_path;
 use deno_runtime::colors;
 
@@ -20,23 +17,27 @@ use crate::graph_util::error_for_any_npm_specifier;
 use crate::proc_state::ProcState;
 use crate::util;
 use crate::util::display;
-use crate::util::file_watcher::ResolutionResult;
 
 pub async fn generate(
   flags: Flags,
   generate_flags: GenerateFlags,
 ) -> Result<(), AnyError> {
   let cli_options = CliOptions::from_flags(flags)?;
-  let source_file =
-    Arc::new(cli_options.argv().get(0).unwrap().to_string()).as_ref();
-  let module_specifier = resolve_url_or_path(source_file)?;
+  let source_file = cli_options.argv().get(0).unwrap().to_string();
+  let module_specifier = resolve_url_or_path(&source_file)?;
   let ps = ProcState::from_options(Arc::new(cli_options)).await?;
-  let graph = create_graph_and_maybe_check(module_specifier, &ps).await?;
+  let graph =
+    create_graph_and_maybe_check(module_specifier.clone(), &ps).await?;
+
+  error_for_any_npm_specifier(&graph)?;
 
-  let lines = util::fs::read_file_to_string(&source_file)
+  let source_path = module_specifier.to_file_path().unwrap();
+  let lines = util::fs::read_file_to_string(&source_path)
     .await?
     .lines()
-    .filter(|l| l.starts_with("//deno:generate"));
+    .filter(|l| l.starts_with("//deno:generate"))
+    .map(str::to_string)
+    .collect::<Vec<_>>();
 
   for line in lines {
     let command = line.trim_start_matches("//deno:generate").trim();
@@ -1,73 +1,404 @@
 // Copyright 2018-2023 the Deno authors. All rights reserved. MIT license.
 
+use deno_config::glob::FilePatterns;
+use deno_config::glob::PathOrPatternSet;
+use deno_core::anyhow::anyhow;
 use deno_core::error::AnyError;
+use deno_core::futures::stream::FuturesUnordered;
+use deno_core::futures::FutureExt;
+use deno_core::futures::StreamExt;
 use deno_core::resolve_url_or_path;
 use deno_core::url::Url;
-use glob::Pattern;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env::consts::OS;
 use std::path::Path;
+use std::path::PathBuf;
 use std::process::Command;
+use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task::spawn_blocking;
+
+use serde::Serialize;
 
 use crate::args::FileFlags;
 use crate::args::Flags;
 use crate::args::GenerateFlags;
 use crate::graph_util::create_graph_and_maybe_check;
 use crate::proc_state::ProcState;
+use crate::util::file_watcher;
+use crate::util::file_watcher::ResolutionResult;
 
 mod parse_comments;
 mod quoted_split;
+mod substitution;
 
 use parse_comments::{parse_comments, ParsedComment};
 
 // https://docs.rs/glob/latest/glob/struct.Pattern.html#method.matches_path_with
 
-/// Runs the `deno generate` commands in the given module.
+/// Metadata about a collected generate command that isn't carried by the
+/// `std::process::Command` itself, kept around so `--json` mode can report
+/// it without re-deriving it from the executed command.
+struct GenerateCommandMeta {
+  module_specifier: Url,
+  line: usize,
+  character: usize,
+  /// The directive text as written in the source, before `$VAR`
+  /// substitution or alias-arg merging.
+  command_full: String,
+  /// The directive text after `$VAR` substitution, before alias-arg
+  /// merging and `quoted_split`.
+  expanded: String,
+  /// The resolved executable that `argv[0]` actually invokes — e.g. the
+  /// `node_modules/.bin` path an `npm:` specifier resolved to, or the
+  /// current `deno` executable for a `jsr:`/`http(s):` specifier — rather
+  /// than the scheme-prefixed directive text.
+  program: String,
+  argv: Vec<String>,
+  env: Vec<String>,
+}
+
+/// One line of `--json` output: the result of a single executed generate
+/// command.
+#[derive(Serialize)]
+struct GenerateCommandJson {
+  module: String,
+  line: usize,
+  character: usize,
+  command: String,
+  expanded: String,
+  program: String,
+  argv: Vec<String>,
+  env: Vec<String>,
+  exit_code: Option<i32>,
+  stdout: String,
+  stderr: String,
+  duration_ms: u128,
+}
+
+/// The final `--json` line: counts across the whole `generate` run.
+#[derive(Serialize, Default)]
+struct GenerateSummaryJson {
+  run: usize,
+  skipped: usize,
+  failed: usize,
+}
+
+/// Runs the `deno generate` commands in the given module, or (with
+/// `--watch`) re-runs them as affected modules change on disk.
 pub async fn generate(
   flags: Flags,
   generate_flags: GenerateFlags,
 ) -> Result<(), AnyError> {
-  let file_filter =
-    file_filter_from_file_flags(&generate_flags.files, |_| true);
+  // Owns its data outright so the same filter can be moved into
+  // `generate_with_watch`'s 'static closures alongside a moved clone of
+  // `generate_flags` itself, without re-walking the filesystem.
+  let included_files = collect_included_files(&generate_flags.files)?;
+  let file_filter = move |path: &Path| included_files.contains(path);
+
   let source_file = resolve_url_or_path(&generate_flags.source_file)?;
-  if !file_filter(path_from_url(&source_file)) {
+  if !path_from_url(&source_file).is_some_and(|path| file_filter(&path)) {
     return Ok(());
   }
 
+  if let Some(watch_flags) = generate_flags.watch.clone() {
+    return generate_with_watch(
+      flags,
+      generate_flags,
+      source_file,
+      watch_flags,
+      file_filter,
+    )
+    .await;
+  }
+
   let ps = ProcState::build(flags).await?;
   let graph =
     Arc::try_unwrap(create_graph_and_maybe_check(source_file, &ps).await?)
       .unwrap();
   let comment_filter =
     comment_filter_from_generate_flags(&generate_flags, |_| true);
+  run_once(
+    &ps,
+    &graph,
+    &generate_flags,
+    &file_filter,
+    &comment_filter,
+    None,
+  )
+  .await
+}
+
+/// Re-runs the `//deno:generate` directives as modules reachable from
+/// `source_file` change on disk, reusing the same `ProcState` across every
+/// iteration (the expensive part to build) and only re-running commands
+/// for the modules whose source actually changed, rather than the whole
+/// graph.
+async fn generate_with_watch(
+  flags: Flags,
+  generate_flags: GenerateFlags,
+  source_file: Url,
+  watch_flags: file_watcher::WatchFlags,
+  file_filter: impl Fn(&Path) -> bool + Clone + 'static,
+) -> Result<(), AnyError> {
+  let ps = ProcState::build(flags.clone()).await?;
+
+  // The --run/--skip regexes only depend on `generate_flags`, which is
+  // unchanged across restarts, so build the filter once up front instead
+  // of recompiling it inside `operation` on every file-change restart.
+  let comment_filter =
+    comment_filter_from_generate_flags(&generate_flags, |_| true);
+
+  let resolver = {
+    let ps = ps.clone();
+    let source_file = source_file.clone();
+    move |_flags: Flags| {
+      let ps = ps.clone();
+      let source_file = source_file.clone();
+      async move {
+        let graph =
+          create_graph_and_maybe_check(source_file, &ps).await?;
+        let paths_to_watch: Vec<PathBuf> = graph
+          .modules()
+          .filter_map(|module| module.specifier.to_file_path().ok())
+          .collect();
+        Ok((graph, paths_to_watch))
+      }
+      .map(|result| match result {
+        Ok((graph, paths_to_watch)) => ResolutionResult::Restart {
+          paths_to_watch,
+          result: Ok(graph),
+        },
+        Err(e) => ResolutionResult::Restart {
+          paths_to_watch: vec![],
+          result: Err(e),
+        },
+      })
+      .boxed_local()
+    }
+  };
+
+  // Tracks the last-seen source of every module so a re-run only executes
+  // the generate commands belonging to modules that actually changed,
+  // instead of re-running the whole graph's directives on every restart.
+  let previous_sources: Rc<RefCell<HashMap<Url, Arc<str>>>> =
+    Rc::new(RefCell::new(HashMap::new()));
+
+  let operation = move |graph: Arc<deno_graph::ModuleGraph>| {
+    let ps = ps.clone();
+    let generate_flags = generate_flags.clone();
+    let file_filter = file_filter.clone();
+    let comment_filter = comment_filter.clone();
+    let previous_sources = previous_sources.clone();
+    async move {
+      let mut changed = HashSet::new();
+      {
+        let mut previous_sources = previous_sources.borrow_mut();
+        for module in graph.modules() {
+          let Some(source) = &module.maybe_source else {
+            continue;
+          };
+          let changed_now = match previous_sources.get(&module.specifier) {
+            Some(previous) => previous.as_ref() != source.as_ref(),
+            None => true,
+          };
+          if changed_now {
+            changed.insert(module.specifier.clone());
+          }
+          previous_sources.insert(module.specifier.clone(), source.clone());
+        }
+      }
+
+      run_once(
+        &ps,
+        &graph,
+        &generate_flags,
+        &file_filter,
+        &comment_filter,
+        Some(&changed),
+      )
+      .await
+    }
+    .boxed_local()
+  };
+
+  file_watcher::watch_func(
+    flags,
+    resolver,
+    operation,
+    file_watcher::PrintConfig::new(
+      "Generate",
+      watch_flags.no_clear_screen.unwrap_or(false),
+    ),
+  )
+  .await
+}
+
+/// Runs every collected generate command once: the body shared by a
+/// one-shot `deno generate` and a single `--watch` iteration.
+///
+/// `only_modules`, when given, restricts execution to modules whose
+/// specifier is in the set — used by `--watch` so a re-run only re-runs
+/// the commands belonging to modules that actually changed.
+async fn run_once(
+  ps: &ProcState,
+  graph: &deno_graph::ModuleGraph,
+  generate_flags: &GenerateFlags,
+  file_filter: &impl Fn(&Path) -> bool,
+  comment_filter: &impl Fn(&ParsedComment) -> bool,
+  only_modules: Option<&HashSet<Url>>,
+) -> Result<(), AnyError> {
   let verbose = generate_flags.verbose.unwrap_or(false);
   let dry_run = generate_flags.dry_run.unwrap_or(false);
   let trace = generate_flags.trace.unwrap_or(false);
+  let keep_going = generate_flags.keep_going.unwrap_or(false);
+  let json = generate_flags.json.unwrap_or(false);
+  let jobs = resolve_jobs(generate_flags.jobs);
+  let semaphore = Arc::new(Semaphore::new(jobs));
+  // Flipped once a command fails and `!keep_going`, so tasks still waiting
+  // on a permit back out instead of starting — the alternative to the
+  // `FuturesUnordered` loop below unconditionally draining every already-
+  // collected command regardless of an earlier failure.
+  let aborted = Arc::new(AtomicBool::new(false));
+  let mut summary = GenerateSummaryJson::default();
+  let mut run_failure: Option<AnyError> = None;
+
+  // Collected across every module up front (rather than run per-module)
+  // so the semaphore-bounded pool below spans the whole generate
+  // invocation — a graph where most modules only contribute one or two
+  // directives would otherwise never see `--jobs` concurrency, since each
+  // module's `FuturesUnordered` would be fully drained before the next
+  // module's commands were even spawned.
+  let mut all_commands: GenerateCommands = Vec::new();
 
   for module in graph.modules() {
     let module_specifier = &module.specifier;
-    if !file_filter(path_from_url(module_specifier)) {
+    // `graph.modules()` routinely includes `https:`/`npm:`/`jsr:` imports
+    // alongside local files — those have no path to filter on, so they're
+    // simply excluded rather than crashing the whole run.
+    if !path_from_url(module_specifier).is_some_and(|path| file_filter(&path))
+    {
       continue;
     }
+    if let Some(only_modules) = only_modules {
+      if !only_modules.contains(module_specifier) {
+        continue;
+      }
+    }
 
-    let generate_commands =
-      collect_generate_commands(module, &generate_flags, &comment_filter)?;
+    let (commands, filtered_out) =
+      collect_generate_commands(module, generate_flags, comment_filter)?;
+    summary.skipped += filtered_out;
 
-    for (parsed_comment, command) in generate_commands {
-      if verbose {
-        println!(
-          "Running {} in <{}>",
-          parsed_comment.command_full(),
-          module_specifier,
-        );
+    if dry_run {
+      for (parsed_comment, _resolved, _meta) in &commands {
+        summary.skipped += 1;
+        if !json {
+          println!(
+            "Running {} in <{}>",
+            parsed_comment.command_full(),
+            module_specifier,
+          );
+        }
       }
+      continue;
+    }
+
+    all_commands.extend(commands);
+  }
 
-      if dry_run {
+  let mut running = all_commands
+    .into_iter()
+    .map(|(parsed_comment, resolved, meta)| {
+      let semaphore = semaphore.clone();
+      let aborted = aborted.clone();
+      let module_specifier = meta.module_specifier.clone();
+      async move {
+        // A resolution failure is already known at collection time — it
+        // never needs a semaphore permit (there's no process to run), so
+        // it's reported immediately rather than waiting in line.
+        let command = match resolved {
+          Ok(command) => command,
+          Err(e) => return Some((parsed_comment, meta, Err(e), Duration::default())),
+        };
+        let _permit = acquire_unless_aborted(semaphore, aborted).await?;
+        if verbose && !json {
+          println!(
+            "Running {} in <{}>",
+            parsed_comment.command_full(),
+            module_specifier,
+          );
+        }
+        let start = Instant::now();
+        let output = spawn_blocking(move || command.output()).await.unwrap();
+        let duration = start.elapsed();
+        Some((parsed_comment, meta, output.map_err(AnyError::from), duration))
+      }
+    })
+    .collect::<FuturesUnordered<_>>();
+
+  // Once `aborted` is set, a task still waiting on a permit backs out
+  // (see `acquire_unless_aborted`) and shows up here as `None`, so this
+  // loop still drains `running` to completion without spawning or waiting
+  // on anything new — already-started commands finish normally (they
+  // can't be un-spawned), but nothing further is allowed to start.
+  while let Some(item) = running.next().await {
+    let Some((parsed_comment, meta, output, duration)) = item else {
+      continue;
+    };
+    // A spawn/IO failure (e.g. the command isn't on PATH) is reported the
+    // same way as a command that ran and exited non-zero: it counts
+    // against `summary.failed` and is subject to `keep_going`, rather
+    // than aborting the whole run out from under the summary.
+    let output = match output {
+      Ok(output) => output,
+      Err(e) => {
+        summary.failed += 1;
+        if !json {
+          log::error!(
+            "Failed to run {}: {}",
+            parsed_comment.command_full(),
+            e,
+          );
+        }
+        if !keep_going && run_failure.is_none() {
+          run_failure = Some(anyhow!(
+            "Generate command failed: {}",
+            parsed_comment.command_full(),
+          ));
+          aborted.store(true, Ordering::Release);
+        }
         continue;
       }
+    };
+    let succeeded = output.status.success();
 
-      let output = command.output()?;
+    if json {
+      println!(
+        "{}",
+        serde_json::to_string(&GenerateCommandJson {
+          module: meta.module_specifier.to_string(),
+          line: meta.line,
+          character: meta.character,
+          command: meta.command_full,
+          expanded: meta.expanded,
+          program: meta.program,
+          argv: meta.argv,
+          env: meta.env,
+          exit_code: output.status.code(),
+          stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+          stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+          duration_ms: duration.as_millis(),
+        })?
+      );
+    } else {
       if verbose || trace {
         let stdout = String::from_utf8_lossy(&output.stdout);
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -79,50 +410,137 @@ pub async fn generate(
         println!("exit status {}", output.status);
       }
     }
+
+    if succeeded {
+      summary.run += 1;
+    } else {
+      summary.failed += 1;
+      if !json {
+        log::error!(
+          "{} exited with {}",
+          parsed_comment.command_full(),
+          output.status,
+        );
+      }
+      if !keep_going && run_failure.is_none() {
+        run_failure = Some(anyhow!(
+          "Generate command failed: {}",
+          parsed_comment.command_full(),
+        ));
+        aborted.store(true, Ordering::Release);
+      }
+    }
+  }
+
+  if json {
+    println!("{}", serde_json::to_string(&summary)?);
+  }
+
+  if let Some(err) = run_failure {
+    return Err(err);
   }
 
   Ok(())
 }
 
-fn path_from_url(url: &Url) -> &'static Path {
-  &url.to_file_path().unwrap().as_path()
+/// Resolves `--jobs`, defaulting to the number of available CPUs and
+/// always clamping to at least 1 — `Semaphore::new(0)` would otherwise
+/// make every command wait forever.
+fn resolve_jobs(jobs: Option<usize>) -> usize {
+  jobs
+    .unwrap_or_else(|| {
+      std::thread::available_parallelism().map_or(1, |n| n.get())
+    })
+    .max(1)
 }
 
-/// Makes a filter function that filters out files that should not be
-/// included in the graph.
-fn file_filter_from_file_flags<'a, F>(
-  file_flags: &'a FileFlags,
-  filter_fn: F,
-) -> impl Fn(&Path) -> bool + 'a
-where
-  F: Fn(&Path) -> bool + 'a,
-{
-  let include_patterns = file_flags
-    .include
-    .iter()
-    .map(|path| Pattern::new(path.to_str().unwrap()).unwrap());
-  let ignore_patterns = file_flags
-    .ignore
+/// Waits for a permit from `semaphore`, unless `aborted` is already set, in
+/// which case it returns `None` without ever calling `acquire_owned` —
+/// and checks again right after acquiring, so a task that was already
+/// waiting when `aborted` flips mid-wait also backs out instead of
+/// starting.
+async fn acquire_unless_aborted(
+  semaphore: Arc<Semaphore>,
+  aborted: Arc<AtomicBool>,
+) -> Option<tokio::sync::OwnedSemaphorePermit> {
+  if aborted.load(Ordering::Acquire) {
+    return None;
+  }
+  let permit = semaphore.acquire_owned().await.unwrap();
+  if aborted.load(Ordering::Acquire) {
+    return None;
+  }
+  Some(permit)
+}
+
+/// A module specifier's local file path, or `None` for a specifier with no
+/// path to filter on at all (an `https:`/`npm:`/`jsr:` import, say) —
+/// `graph.modules()` routinely includes those alongside local files.
+fn path_from_url(url: &Url) -> Option<PathBuf> {
+  url.to_file_path().ok()
+}
+
+/// Walks the base directories of `file_flags.include`, returning every file
+/// that matches an include pattern and no ignore pattern.
+fn collect_included_files(
+  file_flags: &FileFlags,
+) -> Result<HashSet<PathBuf>, AnyError> {
+  let cwd = std::env::current_dir()?;
+  let include = PathOrPatternSet::from_include_relative_path_or_patterns(
+    &cwd,
+    &path_strings(&file_flags.include),
+  )?;
+  let exclude = PathOrPatternSet::from_exclude_relative_path_or_patterns(
+    &cwd,
+    &path_strings(&file_flags.ignore),
+  )?;
+  let file_patterns = FilePatterns {
+    base: cwd,
+    include: Some(include),
+    exclude,
+  };
+
+  let mut files = HashSet::new();
+  for base in file_patterns.base_paths() {
+    walk_included_files(&base, &file_patterns, &mut files)?;
+  }
+  Ok(files)
+}
+
+fn path_strings(paths: &[PathBuf]) -> Vec<String> {
+  paths
     .iter()
-    .map(|path| Pattern::new(path.to_str().unwrap()).unwrap())
-    .collect::<Vec<Pattern>>();
-
-  move |path: &Path| {
-    if !filter_fn(path) {
-      false
-    } else if ignore_patterns
-      .iter()
-      .any(|pattern| pattern.matches(path.to_str().unwrap()))
-    {
-      false
-    } else {
-      include_patterns
-        .clone()
-        .filter(|pattern| pattern.matches(path.to_str().unwrap()))
-        .next()
-        .is_some()
+    .map(|path| path.to_string_lossy().into_owned())
+    .collect()
+}
+
+/// Recursively walks `dir`, matching `file_patterns` inline instead of
+/// pre-expanding the include/exclude globs into a full path list.
+fn walk_included_files(
+  dir: &Path,
+  file_patterns: &FilePatterns,
+  files: &mut HashSet<PathBuf>,
+) -> Result<(), AnyError> {
+  if !dir.is_dir() {
+    if file_patterns.matches_path(dir) {
+      files.insert(dir.to_path_buf());
     }
+    return Ok(());
   }
+
+  for entry in std::fs::read_dir(dir)? {
+    let path = entry?.path();
+    if file_patterns.exclude.matches_path(&path) {
+      continue;
+    }
+    if path.is_dir() {
+      walk_included_files(&path, file_patterns, files)?;
+    } else if file_patterns.matches_path(&path) {
+      files.insert(path);
+    }
+  }
+
+  Ok(())
 }
 
 /// Makes a filter function that filters out comments that should not be
@@ -156,50 +574,328 @@ where
   }
 }
 
-/// Collects and runs the generate commands from the comments in the given module.
+/// How a generate command's target should be resolved into a runnable
+/// `std::process::Command`, based on the scheme prefix of its directive.
+enum GenerateCommandTarget<'a> {
+  /// A bare executable, resolved the same way `Command::new` always has.
+  Bare(&'a str),
+  /// An `npm:` specifier, run out of `node_modules/.bin`.
+  Npm(&'a str),
+  /// A `node:` specifier. This is Node's built-in-module scheme
+  /// (`node:fs`, `node:path`, ...) — it names an importable module, not an
+  /// executable, so there's nothing to run out of `node_modules/.bin`.
+  NodeBuiltin(&'a str),
+  /// A `deno run`-able specifier (`jsr:...`, `https://...`), invoked with
+  /// the current `deno` executable.
+  DenoRun(&'a str),
+}
+
+impl<'a> GenerateCommandTarget<'a> {
+  fn parse(command: &'a str) -> Self {
+    if let Some(package) = command.strip_prefix("npm:") {
+      GenerateCommandTarget::Npm(package)
+    } else if let Some(module) = command.strip_prefix("node:") {
+      GenerateCommandTarget::NodeBuiltin(module)
+    } else if command.starts_with("jsr:")
+      || command.starts_with("http:")
+      || command.starts_with("https:")
+    {
+      GenerateCommandTarget::DenoRun(command)
+    } else {
+      GenerateCommandTarget::Bare(command)
+    }
+  }
+}
+
+/// Builds the `std::process::Command` that runs `cmd`, launching `npm:`
+/// specifiers out of `node_modules/.bin` and `deno run`-able specifiers
+/// through the current `deno` executable instead of handing the scheme
+/// straight to `Command::new`, which would only ever find a globally
+/// installed binary of the same name.
+fn command_for(
+  cmd: &str,
+  cmd_args: &[String],
+  generate_flags: &GenerateFlags,
+) -> Result<Command, AnyError> {
+  let mut command = match GenerateCommandTarget::parse(cmd) {
+    GenerateCommandTarget::Npm(package) => {
+      let node_modules_dir = generate_flags
+        .node_modules_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("node_modules"));
+      Command::new(resolve_npm_bin(&node_modules_dir, package)?)
+    }
+    GenerateCommandTarget::NodeBuiltin(module) => {
+      return Err(anyhow!(
+        "\"node:{}\" is a Node.js built-in module specifier, not an \
+         executable — use an `npm:` package or a bare command instead",
+        module,
+      ));
+    }
+    GenerateCommandTarget::DenoRun(specifier) => {
+      let mut command = Command::new(std::env::current_exe()?);
+      command.arg("run");
+      command.args(generate_flags.permissions.iter().flatten());
+      command.arg(specifier);
+      command
+    }
+    GenerateCommandTarget::Bare(executable) => Command::new(executable),
+  };
+  command.args(cmd_args);
+  Ok(command)
+}
+
+/// Strips an `@version` suffix from an `npm:` package specifier
+/// (e.g. `cowsay@1.5.0` -> `cowsay`, `@scope/tool@1.0.0` -> `@scope/tool`),
+/// matching the directory name npm installs the package under in
+/// `node_modules`.
+fn package_dir_name(package: &str) -> &str {
+  let search_from = if package.starts_with('@') { 1 } else { 0 };
+  match package[search_from..].find('@') {
+    Some(i) => &package[..search_from + i],
+    None => package,
+  }
+}
+
+/// Resolves an `npm:` package specifier to the executable under
+/// `node_modules/.bin` that it actually installs, by reading the bin name
+/// out of the package's own `package.json` rather than assuming it matches
+/// the package name — npm bin names frequently differ from the package
+/// name (`typescript` installs as `tsc`, scoped packages declare arbitrary
+/// names via their `bin` field).
+fn resolve_npm_bin(
+  node_modules_dir: &Path,
+  package: &str,
+) -> Result<PathBuf, AnyError> {
+  let dir_name = package_dir_name(package);
+  let package_json_path = node_modules_dir.join(dir_name).join("package.json");
+  let package_json_text = std::fs::read_to_string(&package_json_path)
+    .map_err(|e| {
+      anyhow!("Failed to read {}: {}", package_json_path.display(), e)
+    })?;
+  let package_json: serde_json::Value = serde_json::from_str(&package_json_text)
+    .map_err(|e| {
+      anyhow!("Failed to parse {}: {}", package_json_path.display(), e)
+    })?;
+
+  let unscoped_name = dir_name.rsplit('/').next().unwrap_or(dir_name);
+  let bin_name = match package_json.get("bin") {
+    // A string `bin` installs a single executable under the package's own
+    // (unscoped) name.
+    Some(serde_json::Value::String(_)) => unscoped_name.to_string(),
+    Some(serde_json::Value::Object(bins)) => {
+      if bins.contains_key(unscoped_name) {
+        unscoped_name.to_string()
+      } else if bins.len() == 1 {
+        bins.keys().next().unwrap().clone()
+      } else {
+        return Err(anyhow!(
+          "npm:{} declares multiple bin entries ({}); use one of them directly instead of the package name",
+          package,
+          bins.keys().cloned().collect::<Vec<_>>().join(", "),
+        ));
+      }
+    }
+    _ => {
+      return Err(anyhow!(
+        "npm:{} does not declare a \"bin\" in its package.json",
+        package,
+      ));
+    }
+  };
+
+  Ok(node_modules_dir.join(".bin").join(bin_name))
+}
+
+/// The generate commands collected from a single module, ready to run
+/// concurrently: alias references are already resolved into their target
+/// command and merged args at collection time, so nothing here depends on
+/// another entry in the list at run time.
+///
+/// The `Command` itself is a `Result` rather than being unwrapped at
+/// collection time: a resolution failure (an ambiguous npm bin, a missing
+/// `"bin"` field, a `node:` builtin misuse) is a property of one specific
+/// command, not the whole run, so it's threaded through to `run_once` and
+/// reported there the same way a process that fails to spawn is — instead
+/// of aborting collection for every other module's commands too.
+type GenerateCommands =
+  Vec<(ParsedComment, Result<Command, AnyError>, GenerateCommandMeta)>;
+
+/// A directive's command and args, after `$VAR` substitution and quoted
+/// splitting but before alias resolution, along with the supporting data
+/// `collect_generate_commands` needs to finish building it into a
+/// `GenerateCommandMeta`.
+struct SplitDirective {
+  command: String,
+  args: Vec<String>,
+  expanded: String,
+  env: Vec<(&'static str, String)>,
+}
+
+/// Splits a directive's substituted text into its command and args,
+/// logging a warning for every `$VAR` that didn't resolve to a known
+/// environment variable first. Returns `None` for a directive with no
+/// fields at all (e.g. blank after substitution).
+fn split_directive(
+  module: &deno_graph::Module,
+  comment: &ParsedComment,
+) -> Result<Option<SplitDirective>, AnyError> {
+  let env = envs_from(module, comment);
+  let (expanded, unknown_vars) =
+    substitution::substitute_vars(comment.directive(), &env);
+  for name in &unknown_vars {
+    log::warn!(
+      "Unknown variable ${} in generate directive at {}:{}:{}",
+      name,
+      module.specifier,
+      comment.line,
+      comment.character,
+    );
+  }
+
+  let fields = quoted_split::quoted_split(&expanded).map_err(|e| {
+    anyhow!(
+      "Failed to parse generate directive at {}:{}:{}: {}",
+      module.specifier,
+      comment.line,
+      comment.character,
+      e,
+    )
+  })?;
+  Ok(fields.split_first().map(|(command, args)| SplitDirective {
+    command: command.clone(),
+    args: args.to_vec(),
+    expanded,
+    env,
+  }))
+}
+
+/// Resolves a directive's `(command, args)` against already-collected
+/// aliases, merging the alias definition's own args ahead of the
+/// directive's: `command` is only treated as an alias reference if it
+/// names a key already in `aliases`, otherwise it's used literally as a
+/// bare command (there's no syntax that distinguishes "this was meant to
+/// reference an alias" from "this happens to share an alias's name").
+fn resolve_alias(
+  aliases: &HashMap<String, (String, Vec<String>)>,
+  command: String,
+  args: Vec<String>,
+) -> (String, Vec<String>) {
+  match aliases.get(&command) {
+    Some((alias_command, alias_args)) => {
+      let mut full_args = alias_args.clone();
+      full_args.extend(args);
+      (alias_command.clone(), full_args)
+    }
+    None => (command, args),
+  }
+}
+
+/// Collects the generate commands from the comments in the given module.
+/// An alias-referencing directive has its args merged with the alias
+/// definition's right here, so the resulting commands are fully resolved
+/// and independent of each other — they can all run in the same
+/// bounded-concurrency pool.
+///
+/// Aliases are collected in a first pass over every `//deno:generate:
+/// <alias>` comment before any directive is resolved against them, so a
+/// directive that consumes an alias defined later in the same module
+/// still resolves correctly instead of silently falling back to running
+/// the alias name as a literal command.
+///
+/// Also returns the number of directives that `filter_fn` (the `--run`/
+/// `--skip` regex filter) excluded, so the caller can count them toward
+/// `summary.skipped`.
 fn collect_generate_commands<'a>(
   module: &'a deno_graph::Module,
   generate_flags: &'a GenerateFlags,
   filter_fn: &'a dyn Fn(&ParsedComment) -> bool,
-) -> Result<Vec<(ParsedComment, &'a mut std::process::Command)>, AnyError> {
-  let source_code = Arc::get_ref(&module.maybe_source.unwrap()).unwrap();
+) -> Result<(GenerateCommands, usize), AnyError> {
+  let source_code = module
+    .maybe_source
+    .as_deref()
+    .ok_or_else(|| anyhow!("module {} has no source", module.specifier))?;
   let comments = parse_comments(source_code);
-  let mut aliases: HashMap<&str, &ParsedComment> = HashMap::new();
-  let mut commands: Vec<&mut std::process::Command> = Vec::new();
+  let mut aliases: HashMap<String, (String, Vec<String>)> = HashMap::new();
+  let mut commands: GenerateCommands = Vec::new();
+  let mut filtered_out = 0;
+
+  for comment in &comments {
+    let Some(alias) = comment.alias() else {
+      continue;
+    };
+    let Some(split) = split_directive(module, comment)? else {
+      continue;
+    };
+    aliases.insert(alias.to_string(), (split.command, split.args));
+  }
+
   for comment in comments {
-    if let Some(alias) = comment.alias() {
-      aliases.insert(alias, &comment);
+    if comment.alias().is_some() {
       continue;
     }
+    let Some(split) = split_directive(module, &comment)? else {
+      continue;
+    };
 
-    if let Some(filter_fn) = filter_fn {
-      if !filter_fn(&comment) {
-        continue;
-      }
+    if !filter_fn(&comment) {
+      filtered_out += 1;
+      continue;
     }
 
-    let (cmd, cmd_args) = match aliases.get(comment.command()) {
-      Some(alias) => {
-        let mut args = alias.args().to_vec();
-        args.extend(comment.args());
-        (alias.command(), args)
+    let (cmd, cmd_args) = resolve_alias(&aliases, split.command, split.args);
+
+    let mut resolved = command_for(&cmd, &cmd_args, generate_flags);
+    // Read back off the fully-built `Command` rather than re-deriving from
+    // `cmd_args`, since `command_for`'s `DenoRun` arm injects its own
+    // `"run"`/permission-flag/specifier args that aren't in `cmd_args` —
+    // this is what actually runs, so it's what `--json` should report. A
+    // resolution failure has no program/argv to report at all.
+    let (program, argv) = match &resolved {
+      Ok(command) => {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let argv = std::iter::once(program.clone())
+          .chain(
+            command
+              .get_args()
+              .map(|arg| arg.to_string_lossy().into_owned()),
+          )
+          .collect();
+        (program, argv)
       }
-      None => (comment.command(), comment.args()),
+      Err(_) => (String::new(), Vec::new()),
+    };
+
+    let meta = GenerateCommandMeta {
+      module_specifier: module.specifier.clone(),
+      line: comment.line,
+      character: comment.character,
+      command_full: comment.command_full(),
+      expanded: split.expanded,
+      program,
+      argv,
+      env: split.env.iter().map(|(key, _)| key.to_string()).collect(),
     };
 
-    let mut command = Command::new(cmd);
-    command.args(cmd_args).envs(envs_from(module, &comment));
-    commands.push((comment, command));
+    if let Ok(command) = &mut resolved {
+      command.envs(split.env);
+    }
+
+    commands.push((comment, resolved, meta));
   }
 
-  Ok(commands)
+  Ok((commands, filtered_out))
 }
 
-/// Returns the environment variables to be passed to the command.
-fn envs_from<'a>(
-  module: &'a deno_graph::Module,
-  comment: &'a ParsedComment,
-) -> Vec<(&'a str, &'a str)> {
+/// Returns the environment variables to be injected into a generate
+/// command's child process. These are also the variables available to
+/// `$NAME`/`${NAME}` substitution in the directive text itself, since a
+/// directive should be able to reference anything its own child process
+/// would see.
+fn envs_from(
+  module: &deno_graph::Module,
+  comment: &ParsedComment,
+) -> Vec<(&'static str, String)> {
   let deno_dir = module
     .specifier
     .to_file_path()
@@ -207,13 +903,390 @@ fn envs_from<'a>(
     .parent()
     .expect("Module path does not have a parent directory")
     .to_str()
-    .expect("Parent directory is not a valid UTF-8 string");
+    .expect("Parent directory is not a valid UTF-8 string")
+    .to_string();
 
   vec![
-    ("DENO_OS", OS),
-    ("DENO_MODULE", &module.specifier),
-    ("DENO_LINE", comment.line),
-    ("DENO_CHARACTER", comment.character)("DENO_DIR", deno_dir),
-    ("DOLLAR", "$"),
+    ("DENO_OS", OS.to_string()),
+    ("DENO_MODULE", module.specifier.to_string()),
+    ("DENO_LINE", comment.line.to_string()),
+    ("DENO_CHARACTER", comment.character.to_string()),
+    ("DENO_DIR", deno_dir),
+    ("DOLLAR", "$".to_string()),
   ]
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Creates a fresh, empty directory under the OS temp dir for a single
+  /// test to write fixture files into, named after both the test and the
+  /// process so concurrently-running tests never collide.
+  fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+      .join(format!("deno_generate_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn test_walk_included_files_matches_include_pattern() {
+    let base = test_dir("walk_include");
+    std::fs::write(base.join("a.ts"), "").unwrap();
+    std::fs::write(base.join("b.txt"), "").unwrap();
+    std::fs::create_dir_all(base.join("sub")).unwrap();
+    std::fs::write(base.join("sub").join("c.ts"), "").unwrap();
+
+    let include = PathOrPatternSet::from_include_relative_path_or_patterns(
+      &base,
+      &["**/*.ts".to_string()],
+    )
+    .unwrap();
+    let exclude = PathOrPatternSet::from_exclude_relative_path_or_patterns(
+      &base,
+      &[],
+    )
+    .unwrap();
+    let file_patterns = FilePatterns {
+      base: base.clone(),
+      include: Some(include),
+      exclude,
+    };
+
+    let mut files = HashSet::new();
+    walk_included_files(&base, &file_patterns, &mut files).unwrap();
+
+    assert!(files.contains(&base.join("a.ts")));
+    assert!(files.contains(&base.join("sub").join("c.ts")));
+    assert!(!files.contains(&base.join("b.txt")));
+
+    std::fs::remove_dir_all(&base).unwrap();
+  }
+
+  #[test]
+  fn test_walk_included_files_skips_excluded_directory() {
+    let base = test_dir("walk_exclude");
+    std::fs::write(base.join("a.ts"), "").unwrap();
+    std::fs::create_dir_all(base.join("ignored")).unwrap();
+    std::fs::write(base.join("ignored").join("d.ts"), "").unwrap();
+
+    let include = PathOrPatternSet::from_include_relative_path_or_patterns(
+      &base,
+      &["**/*.ts".to_string()],
+    )
+    .unwrap();
+    let exclude = PathOrPatternSet::from_exclude_relative_path_or_patterns(
+      &base,
+      &["ignored".to_string()],
+    )
+    .unwrap();
+    let file_patterns = FilePatterns {
+      base: base.clone(),
+      include: Some(include),
+      exclude,
+    };
+
+    let mut files = HashSet::new();
+    walk_included_files(&base, &file_patterns, &mut files).unwrap();
+
+    assert!(files.contains(&base.join("a.ts")));
+    assert!(!files.contains(&base.join("ignored").join("d.ts")));
+
+    std::fs::remove_dir_all(&base).unwrap();
+  }
+
+  #[test]
+  fn test_package_dir_name_unscoped_no_version() {
+    assert_eq!(package_dir_name("cowsay"), "cowsay");
+  }
+
+  #[test]
+  fn test_package_dir_name_unscoped_with_version() {
+    assert_eq!(package_dir_name("cowsay@1.5.0"), "cowsay");
+  }
+
+  #[test]
+  fn test_package_dir_name_scoped_no_version() {
+    assert_eq!(package_dir_name("@scope/tool"), "@scope/tool");
+  }
+
+  #[test]
+  fn test_package_dir_name_scoped_with_version() {
+    assert_eq!(package_dir_name("@scope/tool@1.0.0"), "@scope/tool");
+  }
+
+  #[test]
+  fn test_generate_command_target_parse_npm() {
+    assert!(matches!(
+      GenerateCommandTarget::parse("npm:cowsay"),
+      GenerateCommandTarget::Npm("cowsay")
+    ));
+  }
+
+  #[test]
+  fn test_generate_command_target_parse_node_builtin() {
+    assert!(matches!(
+      GenerateCommandTarget::parse("node:fs"),
+      GenerateCommandTarget::NodeBuiltin("fs")
+    ));
+  }
+
+  #[test]
+  fn test_generate_command_target_parse_jsr() {
+    assert!(matches!(
+      GenerateCommandTarget::parse("jsr:@std/fmt"),
+      GenerateCommandTarget::DenoRun("jsr:@std/fmt")
+    ));
+  }
+
+  #[test]
+  fn test_generate_command_target_parse_http() {
+    assert!(matches!(
+      GenerateCommandTarget::parse("https://deno.land/x/mod.ts"),
+      GenerateCommandTarget::DenoRun("https://deno.land/x/mod.ts")
+    ));
+  }
+
+  #[test]
+  fn test_generate_command_target_parse_bare() {
+    assert!(matches!(
+      GenerateCommandTarget::parse("tsc"),
+      GenerateCommandTarget::Bare("tsc")
+    ));
+  }
+
+  fn write_package_json(node_modules_dir: &Path, dir_name: &str, json: &str) {
+    let package_dir = node_modules_dir.join(dir_name);
+    std::fs::create_dir_all(&package_dir).unwrap();
+    std::fs::write(package_dir.join("package.json"), json).unwrap();
+  }
+
+  #[test]
+  fn test_resolve_npm_bin_string_bin_uses_package_name() {
+    let node_modules_dir = test_dir("resolve_npm_bin_string");
+    write_package_json(
+      &node_modules_dir,
+      "cowsay",
+      r#"{"bin": "./cli.js"}"#,
+    );
+
+    let bin = resolve_npm_bin(&node_modules_dir, "cowsay").unwrap();
+
+    assert_eq!(bin, node_modules_dir.join(".bin").join("cowsay"));
+    std::fs::remove_dir_all(&node_modules_dir).unwrap();
+  }
+
+  #[test]
+  fn test_resolve_npm_bin_object_bin_prefers_matching_name() {
+    let node_modules_dir = test_dir("resolve_npm_bin_object_match");
+    write_package_json(
+      &node_modules_dir,
+      "cowsay",
+      r#"{"bin": {"cowsay": "./bin/cowsay", "cowthink": "./bin/cowthink"}}"#,
+    );
+
+    let bin = resolve_npm_bin(&node_modules_dir, "cowsay").unwrap();
+
+    assert_eq!(bin, node_modules_dir.join(".bin").join("cowsay"));
+    std::fs::remove_dir_all(&node_modules_dir).unwrap();
+  }
+
+  #[test]
+  fn test_resolve_npm_bin_object_bin_multiple_unmatched_entries_errors() {
+    let node_modules_dir = test_dir("resolve_npm_bin_object_ambiguous");
+    write_package_json(
+      &node_modules_dir,
+      "typescript",
+      r#"{"bin": {"tsc": "./bin/tsc", "tsserver": "./bin/tsserver"}}"#,
+    );
+
+    let bin = resolve_npm_bin(&node_modules_dir, "typescript");
+
+    assert!(
+      bin.is_err_and(|e| e.to_string().contains("multiple bin entries"))
+    );
+    std::fs::remove_dir_all(&node_modules_dir).unwrap();
+  }
+
+  #[test]
+  fn test_resolve_npm_bin_object_bin_single_entry_fallback() {
+    let node_modules_dir = test_dir("resolve_npm_bin_object_single");
+    write_package_json(
+      &node_modules_dir,
+      "typescript",
+      r#"{"bin": {"tsc": "./bin/tsc"}}"#,
+    );
+
+    let bin = resolve_npm_bin(&node_modules_dir, "typescript").unwrap();
+
+    assert_eq!(bin, node_modules_dir.join(".bin").join("tsc"));
+    std::fs::remove_dir_all(&node_modules_dir).unwrap();
+  }
+
+  #[test]
+  fn test_resolve_npm_bin_scoped_package() {
+    let node_modules_dir = test_dir("resolve_npm_bin_scoped");
+    write_package_json(
+      &node_modules_dir,
+      "@scope/tool",
+      r#"{"bin": "./cli.js"}"#,
+    );
+
+    let bin = resolve_npm_bin(&node_modules_dir, "@scope/tool@1.0.0").unwrap();
+
+    assert_eq!(
+      bin,
+      node_modules_dir.join(".bin").join("tool"),
+    );
+    std::fs::remove_dir_all(&node_modules_dir).unwrap();
+  }
+
+  #[test]
+  fn test_resolve_npm_bin_no_bin_field_errors() {
+    let node_modules_dir = test_dir("resolve_npm_bin_no_bin");
+    write_package_json(&node_modules_dir, "no-bin", r#"{"name": "no-bin"}"#);
+
+    let bin = resolve_npm_bin(&node_modules_dir, "no-bin");
+
+    assert!(bin.is_err());
+    std::fs::remove_dir_all(&node_modules_dir).unwrap();
+  }
+
+  #[test]
+  fn test_resolve_alias_merges_alias_args_ahead_of_directive_args() {
+    let mut aliases = HashMap::new();
+    aliases.insert(
+      "base".to_string(),
+      ("tool".to_string(), vec!["--flag".to_string()]),
+    );
+
+    let (cmd, args) = resolve_alias(
+      &aliases,
+      "base".to_string(),
+      vec!["extra".to_string()],
+    );
+
+    assert_eq!(cmd, "tool");
+    assert_eq!(args, vec!["--flag".to_string(), "extra".to_string()]);
+  }
+
+  #[test]
+  fn test_resolve_alias_is_order_independent() {
+    // The aliases map passed in is assumed to already be fully populated —
+    // `resolve_alias` itself doesn't care whether the alias it looked up
+    // was defined before or after the directive in the source, which is
+    // what makes `collect_generate_commands`'s two-pass collection (alias
+    // definitions first, directives second) sufficient to support forward
+    // references.
+    let mut aliases = HashMap::new();
+    aliases.insert(
+      "later".to_string(),
+      ("tool".to_string(), vec![]),
+    );
+
+    let (cmd, args) =
+      resolve_alias(&aliases, "later".to_string(), vec!["-x".to_string()]);
+
+    assert_eq!(cmd, "tool");
+    assert_eq!(args, vec!["-x".to_string()]);
+  }
+
+  #[test]
+  fn test_resolve_alias_unknown_name_runs_as_literal_command() {
+    let aliases = HashMap::new();
+
+    let (cmd, args) = resolve_alias(
+      &aliases,
+      "tsc".to_string(),
+      vec!["--build".to_string()],
+    );
+
+    assert_eq!(cmd, "tsc");
+    assert_eq!(args, vec!["--build".to_string()]);
+  }
+
+  #[test]
+  fn test_resolve_jobs_honors_explicit_value() {
+    assert_eq!(resolve_jobs(Some(4)), 4);
+  }
+
+  #[test]
+  fn test_resolve_jobs_clamps_explicit_zero_to_one() {
+    assert_eq!(resolve_jobs(Some(0)), 1);
+  }
+
+  #[test]
+  fn test_resolve_jobs_defaults_to_at_least_one() {
+    assert!(resolve_jobs(None) >= 1);
+  }
+
+  #[tokio::test]
+  async fn test_acquire_unless_aborted_succeeds_when_not_aborted() {
+    let semaphore = Arc::new(Semaphore::new(1));
+    let aborted = Arc::new(AtomicBool::new(false));
+
+    let permit = acquire_unless_aborted(semaphore, aborted).await;
+
+    assert!(permit.is_some());
+  }
+
+  #[tokio::test]
+  async fn test_acquire_unless_aborted_returns_none_when_already_aborted() {
+    let semaphore = Arc::new(Semaphore::new(1));
+    let aborted = Arc::new(AtomicBool::new(true));
+
+    let permit = acquire_unless_aborted(semaphore.clone(), aborted).await;
+
+    assert!(permit.is_none());
+    // Never touched the semaphore, since there was nothing to run.
+    assert_eq!(semaphore.available_permits(), 1);
+  }
+
+  #[test]
+  fn test_generate_summary_json_shape() {
+    let summary = GenerateSummaryJson {
+      run: 2,
+      skipped: 1,
+      failed: 3,
+    };
+
+    let value: serde_json::Value =
+      serde_json::from_str(&serde_json::to_string(&summary).unwrap())
+        .unwrap();
+
+    assert_eq!(value["run"], 2);
+    assert_eq!(value["skipped"], 1);
+    assert_eq!(value["failed"], 3);
+  }
+
+  #[test]
+  fn test_generate_command_json_shape() {
+    let record = GenerateCommandJson {
+      module: "file:///a.ts".to_string(),
+      line: 1,
+      character: 2,
+      command: "//deno:generate tool".to_string(),
+      expanded: "tool".to_string(),
+      program: "/usr/bin/tool".to_string(),
+      argv: vec!["/usr/bin/tool".to_string()],
+      env: vec!["DENO_OS".to_string()],
+      exit_code: Some(0),
+      stdout: "ok".to_string(),
+      stderr: "".to_string(),
+      duration_ms: 5,
+    };
+
+    let value: serde_json::Value =
+      serde_json::from_str(&serde_json::to_string(&record).unwrap())
+        .unwrap();
+
+    assert_eq!(value["module"], "file:///a.ts");
+    assert_eq!(value["program"], "/usr/bin/tool");
+    assert_eq!(value["argv"][0], "/usr/bin/tool");
+    assert_eq!(value["env"][0], "DENO_OS");
+    assert_eq!(value["exit_code"], 0);
+    assert_eq!(value["duration_ms"], 5);
+  }
+}
@@ -0,0 +1,121 @@
+// Copyright 2018-2023 the Deno authors. All rights reserved. MIT license.
+
+use super::quoted_split::quoted_split;
+
+pub const DIRECTIVE_PREFIX: &str = "//deno:generate";
+
+/// A single `//deno:generate` directive parsed out of a module's source
+/// text, along with its position for `DENO_LINE`/`DENO_CHARACTER` and
+/// `--json` reporting.
+pub struct ParsedComment {
+  /// The trimmed source line the directive appears on, including the
+  /// `//deno:generate` prefix and any code before it on that line.
+  pub original: String,
+  /// The name this directive defines as an alias, when it's written as
+  /// `//deno:generate:<alias> ...` rather than a plain directive.
+  alias: Option<String>,
+  /// The directive text after the prefix (and alias marker, if any),
+  /// unexpanded and unsplit.
+  directive: String,
+  pub line: usize,
+  pub character: usize,
+}
+
+impl ParsedComment {
+  pub fn alias(&self) -> Option<&str> {
+    self.alias.as_deref()
+  }
+
+  /// The raw directive text, before `$VAR` substitution or field
+  /// splitting.
+  pub fn directive(&self) -> &str {
+    &self.directive
+  }
+
+  /// The directive's first field, split without `$VAR` substitution; the
+  /// command that's actually run is resolved from the substituted, split
+  /// text in `collect_generate_commands`.
+  pub fn command(&self) -> String {
+    quoted_split(&self.directive)
+      .ok()
+      .and_then(|fields| fields.into_iter().next())
+      .unwrap_or_default()
+  }
+
+  pub fn args(&self) -> Vec<String> {
+    quoted_split(&self.directive)
+      .map(|fields| fields.into_iter().skip(1).collect())
+      .unwrap_or_default()
+  }
+
+  pub fn command_full(&self) -> String {
+    self.original.trim().to_string()
+  }
+}
+
+/// Scans `source` line by line for `//deno:generate` directives.
+pub fn parse_comments(source: &str) -> Vec<ParsedComment> {
+  let mut comments = Vec::new();
+
+  for (line_index, line) in source.lines().enumerate() {
+    let Some(character) = line.find(DIRECTIVE_PREFIX) else {
+      continue;
+    };
+
+    let rest = line[character + DIRECTIVE_PREFIX.len()..].trim_start();
+    let (alias, directive) = match rest.strip_prefix(':') {
+      Some(rest) => {
+        let (alias, directive) =
+          rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        (Some(alias.to_string()), directive.trim_start().to_string())
+      }
+      None => (None, rest.to_string()),
+    };
+
+    comments.push(ParsedComment {
+      original: line.trim().to_string(),
+      alias,
+      directive,
+      line: line_index + 1,
+      character: character + 1,
+    });
+  }
+
+  comments
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parses_a_plain_directive() {
+    let comments = parse_comments("//deno:generate tool --flag\n");
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].command(), "tool");
+    assert_eq!(comments[0].args(), vec!["--flag"]);
+    assert_eq!(comments[0].alias(), None);
+  }
+
+  #[test]
+  fn test_parses_an_alias_directive() {
+    let comments = parse_comments("//deno:generate:base tool --flag\n");
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].alias(), Some("base"));
+    assert_eq!(comments[0].command(), "tool");
+    assert_eq!(comments[0].args(), vec!["--flag"]);
+  }
+
+  #[test]
+  fn test_ignores_lines_without_the_directive() {
+    let comments = parse_comments("// just a comment\nconst x = 1;\n");
+    assert!(comments.is_empty());
+  }
+
+  #[test]
+  fn test_tracks_line_and_character() {
+    let comments = parse_comments("const x = 1;\n  //deno:generate tool\n");
+    assert_eq!(comments[0].line, 2);
+    assert_eq!(comments[0].character, 3);
+  }
+}
@@ -1,40 +1,117 @@
+use std::fmt;
+
 /// Returns true if the given character is a space character.
 pub fn is_space_byte(c: char) -> bool {
   c == ' ' || c == '\t' || c == '\n' || c == '\r'
 }
 
-/// A simple shell-like string splitter that splits on spaces
-/// unless the space is quoted.
+/// An error produced by [`quoted_split`] when the input is malformed, e.g.
+/// an unterminated quoted string.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SplitError {
+  message: String,
+}
+
+impl fmt::Display for SplitError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.message)
+  }
+}
+
+impl std::error::Error for SplitError {}
+
+/// A simple shell-like string splitter that splits on spaces unless the
+/// space is quoted or escaped.
+///
+/// A backslash outside of a quoted string escapes the character that
+/// follows it, so `\"`, `\'`, and `\ ` produce a literal quote or space
+/// inside a field instead of delimiting or opening a quoted string. The
+/// backslash itself is dropped from the result. Quoted strings are left
+/// unescaped, matching the historical behavior of this splitter.
 ///
 /// https://tip.golang.org/src/cmd/internal/quoted/quoted.go
-pub fn quoted_split(s: &str) -> Vec<&str> {
+pub fn quoted_split(s: &str) -> Result<Vec<String>, SplitError> {
+  quoted_split_with_options(s, SplitOptions::default())
+}
+
+/// Options controlling [`quoted_split`]'s behavior.
+pub struct SplitOptions {
+  /// When `true`, a backslash outside a quoted string still prevents the
+  /// character that follows it from delimiting or opening a quoted
+  /// string, but the backslash is kept in the output rather than being
+  /// stripped. This preserves the splitter's pre-escaping behavior for
+  /// callers that relied on a bare `\'` token, without losing the new
+  /// "don't let this character act as a delimiter" semantics.
+  pub no_unescape: bool,
+}
+
+impl Default for SplitOptions {
+  fn default() -> Self {
+    Self { no_unescape: false }
+  }
+}
+
+/// Like [`quoted_split`], but with explicit [`SplitOptions`].
+pub fn quoted_split_with_options(
+  s: &str,
+  options: SplitOptions,
+) -> Result<Vec<String>, SplitError> {
   // Split fields allowing '' or "" around elements.
   // Quotes further inside the string do not count.
-  let mut f: Vec<&str> = vec![];
-  let mut s = s;
-  while s.len() > 0 {
-    while s.len() > 0 && is_space_byte(s.chars().next().unwrap()) {
-      s = &s[1..];
+  let mut f: Vec<String> = vec![];
+  let mut chars = s.chars().peekable();
+
+  loop {
+    while matches!(chars.peek(), Some(&c) if is_space_byte(c)) {
+      chars.next();
     }
-    if s.len() == 0 {
+    if chars.peek().is_none() {
       break;
     }
-    // Accepted quoted string. No unescaping inside.
-    if s.chars().next().unwrap() == '"' || s.chars().next().unwrap() == '\'' {
-      let quote = s.chars().next().unwrap();
-      s = &s[1..];
-      let i = s.find(quote).unwrap_or_else(|| {
-        panic!("unterminated {} string", quote);
-      });
-      f.push(&s[..i]);
-      s = &s[i + 1..];
-      continue;
+
+    let mut field = String::new();
+    loop {
+      match chars.peek().copied() {
+        None => break,
+        Some(c) if is_space_byte(c) => break,
+        // Accepted quoted string. No unescaping inside. A quote only opens
+        // a quoted string at the start of a field, and the closing quote
+        // ends the field.
+        Some(quote) if field.is_empty() && (quote == '"' || quote == '\'') => {
+          chars.next();
+          loop {
+            match chars.next() {
+              Some(c) if c == quote => break,
+              Some(c) => field.push(c),
+              None => {
+                return Err(SplitError {
+                  message: format!("unterminated {} string", quote),
+                })
+              }
+            }
+          }
+          break;
+        }
+        Some('\\') => {
+          chars.next();
+          if options.no_unescape {
+            field.push('\\');
+          }
+          match chars.next() {
+            Some(c) => field.push(c),
+            None => field.push('\\'),
+          }
+        }
+        Some(c) => {
+          chars.next();
+          field.push(c);
+        }
+      }
     }
-    let i = s.chars().position(|c| is_space_byte(c)).unwrap_or(s.len());
-    f.push(&s[..i]);
-    s = &s[i..];
+    f.push(field);
   }
-  f
+
+  Ok(f)
 }
 
 #[cfg(test)]
@@ -43,104 +120,112 @@ mod tests {
 
   #[test]
   fn test_empty_string() {
-    let got = quoted_split("");
-    let want: Vec<&str> = vec![];
+    let got = quoted_split("").unwrap();
+    let want: Vec<String> = vec![];
     assert_eq!(got, want);
   }
 
   #[test]
   fn test_string_with_space() {
-    let got = quoted_split(" ");
-    let want: Vec<&str> = vec![];
+    let got = quoted_split(" ").unwrap();
+    let want: Vec<String> = vec![];
     assert_eq!(got, want);
   }
 
   #[test]
   fn test_string_with_one_word() {
-    let got = quoted_split("a");
-    let want: Vec<&str> = vec!["a"];
-    assert_eq!(got, want);
+    let got = quoted_split("a").unwrap();
+    assert_eq!(got, vec!["a"]);
   }
 
   #[test]
   fn test_string_with_leading_space() {
-    let got = quoted_split(" a");
-    let want: Vec<&str> = vec!["a"];
-    assert_eq!(got, want);
+    let got = quoted_split(" a").unwrap();
+    assert_eq!(got, vec!["a"]);
   }
 
   #[test]
   fn test_string_with_trailing_space() {
-    let got = quoted_split("a ");
-    let want: Vec<&str> = vec!["a"];
-    assert_eq!(got, want);
+    let got = quoted_split("a ").unwrap();
+    assert_eq!(got, vec!["a"]);
   }
 
   #[test]
   fn test_string_with_two_words() {
-    let got = quoted_split("a b");
-    let want: Vec<&str> = vec!["a", "b"];
-    assert_eq!(got, want);
+    let got = quoted_split("a b").unwrap();
+    assert_eq!(got, vec!["a", "b"]);
   }
 
   #[test]
   fn test_string_with_two_words_and_multi_space() {
-    let got = quoted_split("a  b");
-    let want: Vec<&str> = vec!["a", "b"];
-    assert_eq!(got, want);
+    let got = quoted_split("a  b").unwrap();
+    assert_eq!(got, vec!["a", "b"]);
   }
 
   #[test]
   fn test_string_with_two_words_and_tab() {
-    let got = quoted_split("a\tb");
-    let want: Vec<&str> = vec!["a", "b"];
-    assert_eq!(got, want);
+    let got = quoted_split("a\tb").unwrap();
+    assert_eq!(got, vec!["a", "b"]);
   }
 
   #[test]
   fn test_string_with_two_words_and_newline() {
-    let got = quoted_split("a\nb");
-    let want: Vec<&str> = vec!["a", "b"];
-    assert_eq!(got, want);
+    let got = quoted_split("a\nb").unwrap();
+    assert_eq!(got, vec!["a", "b"]);
   }
 
   #[test]
   fn test_string_with_single_quoted_word() {
-    let got = quoted_split("'a b'");
-    let want: Vec<&str> = vec!["a b"];
-    assert_eq!(got, want);
+    let got = quoted_split("'a b'").unwrap();
+    assert_eq!(got, vec!["a b"]);
   }
 
   #[test]
   fn test_string_with_double_quoted_word() {
-    let got = quoted_split(r#""a b""#);
-    let want: Vec<&str> = vec!["a b"];
-    assert_eq!(got, want);
+    let got = quoted_split(r#""a b""#).unwrap();
+    assert_eq!(got, vec!["a b"]);
   }
 
   #[test]
   fn test_string_with_both_quoted_words() {
-    let got = quoted_split(r#"'a '"b ""#);
-    let want: Vec<&str> = vec!["a ", "b "];
-    assert_eq!(got, want);
+    let got = quoted_split(r#"'a '"b ""#).unwrap();
+    assert_eq!(got, vec!["a ", "b "]);
   }
 
   #[test]
   fn test_string_with_quotes_contained_within_each_other() {
-    let got = quoted_split(r#"'a "'"'b""#);
-    let want: Vec<&str> = vec![r#"a ""#, r#"b"#];
-    assert_eq!(got, want);
+    let got = quoted_split(r#"'a "'"'b""#).unwrap();
+    assert_eq!(got, vec![r#"a ""#, r#"'b"#]);
   }
 
   #[test]
   fn test_escaped_single_quote() {
-    let got = quoted_split(r#"\'"#);
+    let got =
+      quoted_split_with_options(r#"\'"#, SplitOptions { no_unescape: true })
+        .unwrap();
     let want: Vec<&str> = vec![r#"\'"#];
     assert_eq!(got, want);
   }
 
+  #[test]
+  fn test_unescaped_quote_becomes_a_literal_field_character() {
+    let got = quoted_split(r#"\'"#).unwrap();
+    assert_eq!(got, vec!["'"]);
+  }
+
+  #[test]
+  fn test_escaped_space_does_not_delimit() {
+    let got = quoted_split(r#"a\ b c"#).unwrap();
+    assert_eq!(got, vec!["a b", "c"]);
+  }
+
   #[test]
   fn test_unterminated_single_quote() {
     assert!(quoted_split("'a").is_err());
   }
+
+  #[test]
+  fn test_unterminated_double_quote() {
+    assert!(quoted_split(r#""a"#).is_err());
+  }
 }
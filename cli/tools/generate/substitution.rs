@@ -0,0 +1,187 @@
+// Copyright 2018-2023 the Deno authors. All rights reserved. MIT license.
+
+/// Expands `$NAME` and `${NAME}` references in `text` against `vars`,
+/// mirroring the textual substitution `go generate` performs on a
+/// directive before splitting it into fields (e.g. `$GOFILE`). `$$` and
+/// `$DOLLAR` expand to a literal `$`. Expansion is skipped while inside a
+/// `'...'` or `"..."` quoted region, since quoting is resolved afterwards
+/// by `quoted_split` and a variable's value may itself contain spaces that
+/// should be re-split rather than treated as one quoted field.
+///
+/// Returns the expanded text along with the names of any `$NAME`
+/// references that didn't match an entry in `vars`. Those are left
+/// untouched in the output so a typo'd variable is reported rather than
+/// silently vanishing.
+pub fn substitute_vars(
+  text: &str,
+  vars: &[(&str, String)],
+) -> (String, Vec<String>) {
+  let mut out = String::with_capacity(text.len());
+  let mut unknown = Vec::new();
+  let mut quote: Option<char> = None;
+  let mut chars = text.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    if let Some(q) = quote {
+      out.push(c);
+      if c == q {
+        quote = None;
+      }
+      continue;
+    }
+
+    // A backslash-escaped quote doesn't open a quoted region here either,
+    // matching `quoted_split`, which treats `\"`/`\'` outside quotes as a
+    // literal, non-delimiting character pair rather than the start of a
+    // quoted string.
+    if c == '\\' {
+      out.push(c);
+      if let Some(next) = chars.next() {
+        out.push(next);
+      }
+      continue;
+    }
+
+    if c == '\'' || c == '"' {
+      quote = Some(c);
+      out.push(c);
+      continue;
+    }
+
+    if c != '$' {
+      out.push(c);
+      continue;
+    }
+
+    match chars.peek().copied() {
+      Some('$') => {
+        chars.next();
+        out.push('$');
+      }
+      Some('{') => {
+        chars.next();
+        let mut name = String::new();
+        let mut closed = false;
+        for c in chars.by_ref() {
+          if c == '}' {
+            closed = true;
+            break;
+          }
+          name.push(c);
+        }
+        if !expand_name(&name, vars, &mut out, &mut unknown) {
+          out.push_str("${");
+          out.push_str(&name);
+          if closed {
+            out.push('}');
+          }
+        }
+      }
+      Some(c0) if c0.is_ascii_alphabetic() || c0 == '_' => {
+        let mut name = String::new();
+        while let Some(&c0) = chars.peek() {
+          if c0.is_ascii_alphanumeric() || c0 == '_' {
+            name.push(c0);
+            chars.next();
+          } else {
+            break;
+          }
+        }
+        if !expand_name(&name, vars, &mut out, &mut unknown) {
+          out.push('$');
+          out.push_str(&name);
+        }
+      }
+      _ => out.push('$'),
+    }
+  }
+
+  (out, unknown)
+}
+
+/// Writes the value of `name` to `out` and returns `true`, or records
+/// `name` as unknown and returns `false` without writing anything.
+fn expand_name(
+  name: &str,
+  vars: &[(&str, String)],
+  out: &mut String,
+  unknown: &mut Vec<String>,
+) -> bool {
+  if name == "DOLLAR" {
+    out.push('$');
+  } else if let Some((_, value)) = vars.iter().find(|(n, _)| *n == name) {
+    out.push_str(value);
+  } else {
+    unknown.push(name.to_string());
+    return false;
+  }
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn vars() -> Vec<(&'static str, String)> {
+    vec![("DENO_DIR", "/tmp/mod".to_string())]
+  }
+
+  #[test]
+  fn test_no_vars() {
+    let (got, unknown) = substitute_vars("tool --flag", &vars());
+    assert_eq!(got, "tool --flag");
+    assert!(unknown.is_empty());
+  }
+
+  #[test]
+  fn test_bare_var() {
+    let (got, unknown) =
+      substitute_vars("tool --out $DENO_DIR/gen.ts", &vars());
+    assert_eq!(got, "tool --out /tmp/mod/gen.ts");
+    assert!(unknown.is_empty());
+  }
+
+  #[test]
+  fn test_braced_var() {
+    let (got, unknown) = substitute_vars("tool ${DENO_DIR}/gen.ts", &vars());
+    assert_eq!(got, "tool /tmp/mod/gen.ts");
+    assert!(unknown.is_empty());
+  }
+
+  #[test]
+  fn test_double_dollar_is_a_literal_dollar() {
+    let (got, unknown) = substitute_vars("a$$b", &vars());
+    assert_eq!(got, "a$b");
+    assert!(unknown.is_empty());
+  }
+
+  #[test]
+  fn test_dollar_dollar_variable_expands_to_a_literal_dollar() {
+    let (got, unknown) = substitute_vars("$DOLLAR is money", &vars());
+    assert_eq!(got, "$ is money");
+    assert!(unknown.is_empty());
+  }
+
+  #[test]
+  fn test_unknown_var_is_reported_and_left_untouched() {
+    let (got, unknown) = substitute_vars("tool $NOT_A_VAR", &vars());
+    assert_eq!(got, "tool $NOT_A_VAR");
+    assert_eq!(unknown, vec!["NOT_A_VAR".to_string()]);
+  }
+
+  #[test]
+  fn test_quoted_region_is_not_expanded() {
+    let (got, unknown) = substitute_vars("tool '$DENO_DIR'", &vars());
+    assert_eq!(got, "tool '$DENO_DIR'");
+    assert!(unknown.is_empty());
+  }
+
+  #[test]
+  fn test_escaped_quote_does_not_open_a_quoted_region() {
+    // Matches quoted_split, which also treats an escaped quote as a
+    // literal character rather than the start of a quoted string.
+    let (got, unknown) = substitute_vars(r#"tool \"$DENO_DIR"#, &vars());
+    assert_eq!(got, r#"tool \"/tmp/mod"#);
+    assert!(unknown.is_empty());
+  }
+}